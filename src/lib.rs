@@ -1,8 +1,12 @@
-//! Provides [PacCell] (a cell of a parent and a child).
+//! Provides [PacCell] (a cell of a parent and a child) and [PacChain3]
+//! (a three-tier root → child → grandchild chain).
 
 use std::marker::PhantomPinned;
 use std::ptr::NonNull;
-use std::{cell::OnceCell, pin::Pin};
+use std::{
+    cell::{BorrowError, BorrowMutError, OnceCell, RefCell},
+    pin::Pin,
+};
 
 /// A cell of a parent and a child, which is created by mutably borrowing the parent.
 /// While the parent is in the cell, it cannot be accessed in any way.
@@ -49,7 +53,11 @@ struct PacInner<P, C> {
     /// Child has to be defined before the parent, so it is dropped
     /// before the parent
     child: OnceCell<C>,
-    parent: P,
+
+    /// Parent lives in its own [Box] so its address stays fixed even when the
+    /// inner struct is rebuilt (see [PacCell::map]); the child's pointers into
+    /// it therefore survive such a projection.
+    parent: Pin<Box<P>>,
 
     /// Mark this struct as non-movable. Not really needed, since we always
     /// have it in `Pin<Box<_>>``, but there is no hard in being too explicit.
@@ -72,9 +80,9 @@ impl<'p, P: 'p, C> PacCell<P, C> {
     where
         F: FnOnce(&'p mut P) -> Result<C, E>,
     {
-        // move engine into the struct and pin the struct on heap
+        // move engine into its own box and pin the inner struct on heap
         let inner = PacInner {
-            parent,
+            parent: Box::pin(parent),
             child: OnceCell::new(),
             _pin: PhantomPinned,
         };
@@ -84,7 +92,7 @@ impl<'p, P: 'p, C> PacCell<P, C> {
         // SAFETY: generally this would be unsafe, since one could obtain multiple mut refs this way.
         //   But because we don't allow any access to engine, this mut reference is guaranteed
         //   to be the only one.
-        let mut parent_ref = NonNull::from(&inner.as_mut().parent);
+        let mut parent_ref = NonNull::from(&*inner.as_mut().parent);
         let parent_ref = unsafe { parent_ref.as_mut() };
 
         // create fuel and move it into the struct
@@ -113,6 +121,538 @@ impl<'p, P: 'p, C> PacCell<P, C> {
         // SAFETY: this is safe because child is dropped when this function finishes,
         //    but parent still exists.
         let inner = unsafe { Pin::into_inner_unchecked(self.0) };
-        inner.parent
+        let PacInner { child, parent, .. } = *inner;
+        // drop the child (and its borrow of the parent) before moving the parent out
+        drop(child);
+        // SAFETY: the parent is no longer borrowed, so we may move it out of its box.
+        *unsafe { Pin::into_inner_unchecked(parent) }
+    }
+
+    /// Projects the stored child down to a sub-value `C2`, keeping the parent
+    /// alive and pinned underneath.
+    ///
+    /// This mirrors `owning_ref`'s `OwningRef::map`: a [PacCell] built for one
+    /// child can be narrowed to, say, a single field of it without rebuilding
+    /// the whole cell. The parent keeps its own box, so any interior pointers
+    /// the new child holds into it stay valid. The old child is consumed by
+    /// value so that a reference it holds into the parent can be re-exposed as
+    /// the new child; it is dropped once `f` returns.
+    ///
+    /// ```
+    /// struct Engine {
+    ///     readings: Vec<f64>,
+    /// }
+    /// struct Fuel<'e> {
+    ///     engine: &'e mut Engine,
+    /// }
+    ///
+    /// let cell = pac_cell::PacCell::new(Engine { readings: vec![1.0, 2.0] }, |e| Fuel { engine: e });
+    ///
+    /// // project down to a single reading, keeping the engine alive underneath
+    /// let mut cell = cell.map(|fuel| &mut fuel.engine.readings[1]);
+    /// cell.with_mut(|reading| **reading = 9.0);
+    ///
+    /// let engine = cell.unwrap();
+    /// assert_eq!(engine.readings, vec![1.0, 9.0]);
+    /// ```
+    ///
+    /// Note this takes `f: impl FnOnce(C) -> C2`, consuming the old child by
+    /// value, rather than `FnOnce(&mut C) -> C2`: the new child is often a
+    /// borrow carved out of the old one (as in the example above), and `f`
+    /// needs to own the old child to hand such a borrow back out.
+    pub fn map<C2, F>(self, f: F) -> PacCell<P, C2>
+    where
+        F: FnOnce(C) -> C2,
+    {
+        self.try_map::<C2, _, ()>(|c| Ok(f(c))).unwrap()
+    }
+
+    /// Like [map](Self::map), but the projection may fail; on error the cell is
+    /// consumed and the parent dropped.
+    pub fn try_map<C2, F, E>(self, f: F) -> Result<PacCell<P, C2>, E>
+    where
+        F: FnOnce(C) -> Result<C2, E>,
+    {
+        let inner = unsafe { Pin::into_inner_unchecked(self.0) };
+        let PacInner { child, parent, .. } = *inner;
+
+        // consume the old child to produce the new one while the parent box
+        // stays put, so a reference it holds into the parent can be re-exposed
+        let new_child = f(child.into_inner().unwrap())?;
+
+        // reuse the parent's box (not reallocated) so its address stays fixed
+        let rebuilt = Box::pin(PacInner {
+            child: OnceCell::new(),
+            parent,
+            _pin: PhantomPinned,
+        });
+        let _ = rebuilt.child.set(new_child);
+
+        Ok(PacCell(rebuilt))
+    }
+
+    /// Tear the cell down, running a finalizer on the child and returning its
+    /// result together with the parent.
+    ///
+    /// Unlike [unwrap](Self::unwrap), which silently drops the child, this lets
+    /// the caller observe the child's final state in the same move that returns
+    /// the parent. The finalizer sees the child while the parent is still pinned
+    /// in place, so reads through the child's borrow are valid; the child is
+    /// then dropped before the parent is moved out of its box. This is the
+    /// infallible counterpart of [try_unwrap](Self::try_unwrap).
+    ///
+    /// Note the signature is `f: impl FnOnce(&mut C) -> R` returning `(R, P)`,
+    /// rather than the `FnOnce(C, P) -> R` one might expect: an earlier attempt
+    /// at handing the finalizer the parent and child by value segfaulted,
+    /// because the parent had already been moved out of its box (invalidating
+    /// the child's borrow into it) before the finalizer ran. Borrowing the
+    /// child instead keeps the parent pinned in place for the finalizer's call.
+    ///
+    /// ```
+    /// struct Log {
+    ///     lines: Vec<String>,
+    /// }
+    /// struct Cursor<'l> {
+    ///     log: &'l mut Log,
+    ///     position: usize,
+    /// }
+    ///
+    /// let mut cell = pac_cell::PacCell::new(
+    ///     Log { lines: vec!["a".into(), "b".into(), "c".into()] },
+    ///     |log| Cursor { log, position: 0 },
+    /// );
+    /// cell.with_mut(|cursor| cursor.position += 1);
+    ///
+    /// // read how far the cursor advanced before the cell is torn down
+    /// let (position, log) = cell.unwrap_with(|cursor| cursor.position);
+    /// assert_eq!(position, 1);
+    /// assert_eq!(log.lines.len(), 3);
+    /// ```
+    pub fn unwrap_with<R, F>(self, f: F) -> (R, P)
+    where
+        F: FnOnce(&mut C) -> R,
+    {
+        self.try_unwrap::<R, (), _>(|c| Ok(f(c))).unwrap()
+    }
+
+    /// Run a fallible finalizer on the child before it is dropped, then return
+    /// its result together with the parent.
+    ///
+    /// The finalizer sees the child while the parent is still pinned in place,
+    /// so reads through the child's borrow are valid. Afterwards the child is
+    /// dropped and the parent is moved out of its box. On error the child and
+    /// the parent are both dropped.
+    ///
+    /// ```
+    /// #[derive(Debug)]
+    /// struct Account {
+    ///     balance: i64,
+    /// }
+    /// struct Ledger<'a> {
+    ///     account: &'a mut Account,
+    /// }
+    ///
+    /// let mut cell = pac_cell::PacCell::new(Account { balance: 100 }, |a| Ledger { account: a });
+    /// cell.with_mut(|ledger| ledger.account.balance -= 150);
+    ///
+    /// // refuse to tear down when the finalizer's check fails; account and ledger are dropped
+    /// let err = cell
+    ///     .try_unwrap(|ledger| {
+    ///         if ledger.account.balance < 0 {
+    ///             Err("overdrawn")
+    ///         } else {
+    ///             Ok(())
+    ///         }
+    ///     })
+    ///     .unwrap_err();
+    /// assert_eq!(err, "overdrawn");
+    /// ```
+    pub fn try_unwrap<R, E, F>(self, finalize: F) -> Result<(R, P), E>
+    where
+        F: FnOnce(&mut C) -> Result<R, E>,
+    {
+        let inner = unsafe { Pin::into_inner_unchecked(self.0) };
+        let PacInner {
+            mut child, parent, ..
+        } = *inner;
+
+        // read the child's final state while the parent is still in place
+        let result = finalize(child.get_mut().unwrap());
+
+        // drop the child (and its borrow of the parent) before moving the parent out
+        drop(child);
+
+        let value = result?;
+        // SAFETY: the parent is no longer borrowed by the child.
+        let parent = *unsafe { Pin::into_inner_unchecked(parent) };
+        Ok((value, parent))
+    }
+}
+
+/// Like [PacCell], but the child is stored behind `RefCell`-style borrow
+/// bookkeeping so it can be shared.
+///
+/// Where [PacCell::with_mut] only hands out exclusive access, this variant
+/// allows any number of simultaneous `&C` reads alongside occasional `&mut C`,
+/// with conflicts detected at runtime rather than at compile time. The pinned,
+/// non-movable parent storage is identical to [PacCell]; only the child slot
+/// differs.
+///
+/// ## Examples
+///
+/// ```
+/// struct Hello {
+///     world: i64,
+/// }
+///
+/// let mut pac = pac_cell::PacRefCell::new(Hello { world: 10 }, |h| &mut h.world);
+///
+/// pac.with_ref(|world| assert_eq!(**world, 10));
+/// pac.with_mut(|world| **world = 12);
+/// pac.with_ref(|world| assert_eq!(**world, 12));
+/// ```
+pub struct PacRefCell<P, C>(Pin<Box<RefInner<P, C>>>);
+
+/// Inner object of [PacRefCell].
+///
+/// ## Safety
+///
+/// While this struct exists, the parent is considered mutably borrowed.
+/// Therefore, any access to parent is UB.
+///
+/// Because child might contain pointers to parent, this struct cannot
+/// be moved.
+struct RefInner<P, C> {
+    /// Child has to be defined before the parent, so it is dropped
+    /// before the parent.
+    child: RefCell<Option<C>>,
+
+    /// Parent lives in its own [Box] so its address stays fixed even once
+    /// `unwrap` destructures this struct (mirrors why [PacCell]'s `parent` is
+    /// boxed); the child's pointer into it therefore survives the move.
+    parent: Pin<Box<P>>,
+
+    _pin: PhantomPinned,
+}
+
+impl<'p, P: 'p, C> PacRefCell<P, C> {
+    /// Creates the cell by moving the parent into a [Box] and then calling
+    /// the child constructor.
+    pub fn new<F>(parent: P, child_constructor: F) -> Self
+    where
+        F: FnOnce(&'p mut P) -> C,
+    {
+        Self::try_new::<_, ()>(parent, |p| Ok(child_constructor(p))).unwrap()
+    }
+
+    /// Creates the cell by moving the parent into a [Box] and then calling
+    /// the child constructor.
+    pub fn try_new<F, E>(parent: P, child_constructor: F) -> Result<Self, E>
+    where
+        F: FnOnce(&'p mut P) -> Result<C, E>,
+    {
+        let inner = RefInner {
+            parent: Box::pin(parent),
+            child: RefCell::new(None),
+            _pin: PhantomPinned,
+        };
+        let mut inner = Box::pin(inner);
+
+        // create mut reference to parent, without borrowing the struct
+        // SAFETY: generally this would be unsafe, since one could obtain multiple mut refs this way.
+        //   But because we don't allow any access to parent, this mut reference is guaranteed
+        //   to be the only one.
+        let mut parent_ref = NonNull::from(&*inner.as_mut().parent);
+        let parent_ref = unsafe { parent_ref.as_mut() };
+
+        // create child and move it into the struct
+        let child = child_constructor(parent_ref)?;
+        *inner.child.borrow_mut() = Some(child);
+
+        Ok(PacRefCell(inner))
+    }
+
+    /// Executes a function with a shared reference to the child.
+    ///
+    /// Panics if the child is currently mutably borrowed.
+    pub fn with_ref<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&C) -> R,
+    {
+        self.try_with_ref(f)
+            .expect("child is already mutably borrowed")
+    }
+
+    /// Executes a function with a mutable reference to the child.
+    ///
+    /// Panics if the child is currently borrowed.
+    pub fn with_mut<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut C) -> R,
+    {
+        self.try_with_mut(f).expect("child is already borrowed")
+    }
+
+    /// Like [with_ref](Self::with_ref), but returns a [BorrowError] instead of
+    /// panicking when the child is already mutably borrowed.
+    pub fn try_with_ref<F, R>(&self, f: F) -> Result<R, BorrowError>
+    where
+        F: FnOnce(&C) -> R,
+    {
+        let inner = self.inner();
+        let child = inner.child.try_borrow()?;
+        Ok(f(child.as_ref().unwrap()))
+    }
+
+    /// Like [with_mut](Self::with_mut), but returns a [BorrowMutError] instead
+    /// of panicking when the child is already borrowed.
+    pub fn try_with_mut<F, R>(&self, f: F) -> Result<R, BorrowMutError>
+    where
+        F: FnOnce(&mut C) -> R,
+    {
+        let inner = self.inner();
+        let mut child = inner.child.try_borrow_mut()?;
+        Ok(f(child.as_mut().unwrap()))
+    }
+
+    /// Drop the child and return the parent.
+    pub fn unwrap(self) -> P {
+        // SAFETY: this is safe because child is dropped below, before the
+        //    parent is moved out, so no borrow of the parent outlives it.
+        let inner = unsafe { Pin::into_inner_unchecked(self.0) };
+        let RefInner { child, parent, .. } = *inner;
+        // drop the child (and its borrow of the parent) before moving the parent out
+        drop(child);
+        // SAFETY: the parent is no longer borrowed, so we may move it out of its box.
+        *unsafe { Pin::into_inner_unchecked(parent) }
+    }
+
+    /// Shared access to the pinned inner object.
+    fn inner(&self) -> &RefInner<P, C> {
+        // SAFETY: this is safe because we don't move the inner pinned object
+        self.0.as_ref().get_ref()
+    }
+}
+
+/// A three-tier chain of a root, a child created by mutably borrowing the root,
+/// and a grandchild created by mutably borrowing the child.
+///
+/// This extends [PacCell] to the case where the child itself yields a further
+/// borrow, as in `Car → Engine<'car> → Fuel<'car, 'engine>`, without flattening
+/// the two lifetimes into one. While the chain exists, neither the root nor the
+/// child can be accessed directly; only the grandchild is reachable, via
+/// [with_mut](PacChain3::with_mut). Drop order is grandchild, then child, then
+/// root.
+///
+/// ## Examples
+///
+/// ```
+/// struct Root {
+///     value: i64,
+/// }
+/// struct Child<'r> {
+///     root: &'r mut Root,
+/// }
+/// struct Grandchild<'r, 'c> {
+///     child: &'c mut Child<'r>,
+/// }
+///
+/// let mut chain = pac_cell::PacChain3::new(
+///     Root { value: 1 },
+///     |root| Child { root },
+///     |child| Grandchild { child },
+/// );
+///
+/// chain.with_mut(|g| g.child.root.value = 42);
+///
+/// let root = chain.unwrap();
+/// assert_eq!(root.value, 42);
+/// ```
+pub struct PacChain3<A, B, C>(Pin<Box<Chain3Inner<A, B, C>>>);
+
+/// Inner object of [PacChain3].
+///
+/// ## Safety
+///
+/// While this struct exists, the root is considered mutably borrowed by the
+/// child and the child mutably borrowed by the grandchild. Any direct access to
+/// root or child is therefore UB.
+///
+/// Because child and grandchild might contain pointers to what precedes them,
+/// this struct cannot be moved.
+struct Chain3Inner<A, B, C> {
+    /// Fields are ordered so that they drop grandchild, then child, then root.
+    grandchild: OnceCell<C>,
+
+    /// Child lives in its own [Box] so its address stays fixed even once
+    /// `unwrap` destructures this struct; the grandchild's pointer into it
+    /// therefore survives the move (same reasoning as [PacCell]'s boxed
+    /// `parent`).
+    child: Pin<Box<OnceCell<B>>>,
+
+    /// Root lives in its own [Box] for the same reason: the child's pointer
+    /// into it must survive `unwrap` destructuring this struct.
+    root: Pin<Box<A>>,
+
+    _pin: PhantomPinned,
+}
+
+impl<'a, 'b, A: 'a, B: 'b, C> PacChain3<A, B, C> {
+    /// Creates the chain by moving the root into a pinned [Box], constructing
+    /// the child from it, storing the child, and then constructing the
+    /// grandchild from the stored child.
+    pub fn new<FB, FC>(root: A, build_child: FB, build_grandchild: FC) -> Self
+    where
+        FB: FnOnce(&'a mut A) -> B,
+        FC: FnOnce(&'b mut B) -> C,
+    {
+        let inner = Chain3Inner {
+            root: Box::pin(root),
+            child: Box::pin(OnceCell::new()),
+            grandchild: OnceCell::new(),
+            _pin: PhantomPinned,
+        };
+        let mut inner = Box::pin(inner);
+
+        // create mut reference to root, without borrowing the struct
+        // SAFETY: this is the only reference to root, since the chain never
+        //   allows any other access to it.
+        let mut root_ref = NonNull::from(&*inner.as_mut().root);
+        let root_ref = unsafe { root_ref.as_mut() };
+
+        let child = build_child(root_ref);
+        let _ = (*inner.child).set(child);
+
+        // create mut reference to the stored child, without borrowing the struct
+        // SAFETY: the child is likewise only ever reached through the grandchild,
+        //   so this is its only reference.
+        let mut child_ref = NonNull::from(inner.child.get().unwrap());
+        let child_ref = unsafe { child_ref.as_mut() };
+
+        let grandchild = build_grandchild(child_ref);
+        let _ = inner.grandchild.set(grandchild);
+
+        PacChain3(inner)
+    }
+
+    /// Executes a function with a mutable reference to the grandchild.
+    pub fn with_mut<F, R>(&mut self, f: F) -> R
+    where
+        F: FnOnce(&mut C) -> R,
+    {
+        let mut_ref: Pin<&mut Chain3Inner<A, B, C>> = Pin::as_mut(&mut self.0);
+
+        // SAFETY: this is safe because we don't move the inner pinned object
+        let inner = unsafe { Pin::get_unchecked_mut(mut_ref) };
+        let grandchild = inner.grandchild.get_mut().unwrap();
+
+        f(grandchild)
+    }
+
+    /// Drop the grandchild and the child and return the root.
+    pub fn unwrap(self) -> A {
+        // SAFETY: this is safe because grandchild and child are dropped below,
+        //    before the root is moved out, so no borrow of the root outlives it.
+        let inner = unsafe { Pin::into_inner_unchecked(self.0) };
+        let Chain3Inner {
+            grandchild,
+            child,
+            root,
+            ..
+        } = *inner;
+        // tear down leaf-first: the grandchild borrows the child and the child
+        // borrows the root, so both must be dropped before the root moves out.
+        drop(grandchild);
+        drop(child);
+        // SAFETY: the root is no longer borrowed by the child, so we may move
+        //    it out of its box.
+        *unsafe { Pin::into_inner_unchecked(root) }
     }
 }
+
+/// Declares a newtype that bundles an owner with a dependent constructed by
+/// mutably borrowing it, wrapping a [PacCell].
+///
+/// The dependent type must be a type alias with a single lifetime parameter
+/// that names the borrow of the owner, e.g. `type Dep<'o> = &'o mut i64;`.
+/// The macro stores it as `Dep<'static>` and hides the `transmute`-to-`'static`
+/// laundering that would otherwise have to be written by hand for every such
+/// pair (see the `EngineAndFuel` example in the crate tests). This plays the
+/// same role for owner/child bundling as `owning_ref`'s `OwningRef` does for
+/// owner/reference bundling.
+///
+/// The generated type exposes `new`, `with_mut`, and `into_owned`:
+///
+/// ```
+/// type Dep<'o> = &'o mut i64;
+///
+/// pac_cell::pac_cell!(
+///     struct Hello {
+///         owner: i64,
+///         dependent: Dep,
+///     }
+/// );
+///
+/// let mut pac = Hello::new(10, |h| h);
+/// let initial = pac.with_mut(|dep| {
+///     let i = **dep;
+///     **dep = 12;
+///     i
+/// });
+/// assert_eq!(initial, 10);
+/// assert_eq!(pac.into_owned(), 12);
+/// ```
+#[macro_export]
+macro_rules! pac_cell {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            owner: $owner:ty,
+            dependent: $dep:ident $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name($crate::PacCell<$owner, $dep<'static>>);
+
+        impl $name {
+            /// Moves the owner into the cell and constructs the dependent by
+            /// mutably borrowing it.
+            $vis fn new<F>(owner: $owner, constructor: F) -> Self
+            where
+                F: for<'o> ::core::ops::FnOnce(&'o mut $owner) -> $dep<'o>,
+            {
+                let cell = $crate::PacCell::new(owner, |owner: &mut $owner| {
+                    let dependent: $dep<'_> = constructor(owner);
+                    // SAFETY: launder the dependent's borrow of the owner up to
+                    //   'static. The owner is pinned inside the cell and is never
+                    //   accessed while the dependent lives, so the borrow stays
+                    //   valid for as long as the dependent is stored.
+                    unsafe {
+                        ::core::mem::transmute::<$dep<'_>, $dep<'static>>(dependent)
+                    }
+                });
+                $name(cell)
+            }
+
+            /// Executes a function with a mutable reference to the dependent.
+            $vis fn with_mut<F, R>(&mut self, f: F) -> R
+            where
+                F: for<'o> ::core::ops::FnOnce(&mut $dep<'o>) -> R,
+            {
+                self.0.with_mut(|dependent: &mut $dep<'static>| {
+                    // SAFETY: shrink the laundered 'static borrow back down to a
+                    //   local lifetime before exposing it to the caller.
+                    let dependent: &mut $dep<'_> = unsafe {
+                        ::core::mem::transmute::<&mut $dep<'static>, &mut $dep<'_>>(dependent)
+                    };
+                    f(dependent)
+                })
+            }
+
+            /// Drops the dependent and returns the owner.
+            $vis fn into_owned(self) -> $owner {
+                self.0.unwrap()
+            }
+        }
+    };
+}
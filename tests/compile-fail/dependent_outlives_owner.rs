@@ -0,0 +1,19 @@
+//! The dependent may only borrow the owner, never some shorter-lived local.
+//! The `for<'o>` bound on the generated `new` forces the returned `Dep<'o>` to
+//! be tied to the owner's borrow, so laundering an unrelated borrow to `'static`
+//! is rejected.
+
+type Dep<'o> = &'o mut i64;
+
+pac_cell::pac_cell!(
+    struct Hello {
+        owner: i64,
+        dependent: Dep,
+    }
+);
+
+fn main() {
+    let mut local = 5;
+    // The constructor must return a borrow of `owner`, not of `local`.
+    let _pac = Hello::new(10, |_owner| &mut local);
+}
@@ -62,41 +62,41 @@ use car::{Engine, Fuel};
 
 struct FuelDep<'engine>(pub Fuel<'engine, 'engine>);
 
-#[repr(transparent)]
-struct EngineAndFuel<'car> {
-    inner: std::pin::Pin<Box<pac_cell::PacInner<FuelDep<'static>, Engine<'car>>>>,
+/// Bundles an [Engine] with the [Fuel] borrowed from it, built on top of
+/// [pac_cell::PacCell]. The borrow is laundered to `'static` the same way
+/// `pac_cell::pac_cell!` does internally; this is spelled out by hand rather
+/// than via the macro because this owner type carries its own `'car` lifetime
+/// parameter, which the macro's generated struct cannot.
+pub struct EngineAndFuel<'car> {
+    inner: pac_cell::PacCell<Engine<'car>, FuelDep<'static>>,
 }
 impl<'car> EngineAndFuel<'car> {
-    fn new(
-        parent: Engine<'car>,
-        child_constructor: impl for<'a> ::core::ops::FnOnce(&'a mut Engine<'car>) -> FuelDep<'a>,
-    ) -> Self {
-        let inner = pac_cell::PacInner {
-            parent,
-            child: std::cell::OnceCell::new(),
-            _pin: std::marker::PhantomPinned,
-        };
-        let mut inner = Box::pin(inner);
-        let mut parent_ref = std::ptr::NonNull::from(&inner.as_mut().parent);
-        let parent_ref: &mut Engine<'car> = unsafe { parent_ref.as_mut() };
-
-        let child = child_constructor(parent_ref) as FuelDep<'static>;
-        let _ = inner.child.set(child);
+    fn new(parent: Engine<'car>) -> Self {
+        let inner = pac_cell::PacCell::new(parent, |parent: &mut Engine<'car>| {
+            let dep = FuelDep(parent.get_fuel());
+            // SAFETY: launder the dependent's borrow of the parent up to
+            //   'static. The parent is pinned inside the cell and is never
+            //   accessed while the dependent lives, so the borrow stays
+            //   valid for as long as the dependent is stored.
+            unsafe { std::mem::transmute::<FuelDep<'_>, FuelDep<'static>>(dep) }
+        });
 
         EngineAndFuel { inner }
     }
 
     fn with_mut<R>(&mut self, f: impl FnOnce(&mut FuelDep<'_>) -> R) -> R {
-        let mut_ref: std::pin::Pin<&mut pac_cell::PacInner<FuelDep, Engine<'car>>> =
-            std::pin::Pin::as_mut(&mut self.inner);
-        let inner = unsafe { std::pin::Pin::get_unchecked_mut(mut_ref) };
-        let fuel = inner.child.get_mut().unwrap();
-        f(fuel)
+        self.inner.with_mut(|dep: &mut FuelDep<'static>| {
+            // SAFETY: shrink the laundered 'static borrow back down to a
+            //   local lifetime before exposing it to the caller.
+            let dep: &mut FuelDep<'_> =
+                unsafe { std::mem::transmute::<&mut FuelDep<'static>, &mut FuelDep<'_>>(dep) };
+            f(dep)
+        })
     }
 
+    #[allow(dead_code)]
     fn into_owned(self) -> Engine<'car> {
-        let inner = unsafe { std::pin::Pin::into_inner_unchecked(self.inner) };
-        inner.parent
+        self.inner.unwrap()
     }
 }
 
@@ -107,14 +107,10 @@ impl GetFluid for car::Car {
         // create engine by borrowing self
         let engine: car::Engine<'a> = self.get_engine();
 
-        EngineAndFuel::new(engine, init_fuel_dep)
+        EngineAndFuel::new(engine)
     }
 }
 
-fn init_fuel_dep<'e, 'car: 'e>(e: &'e mut Engine<'car>) -> FuelDep<'e> {
-    FuelDep(e.get_fuel())
-}
-
 #[test]
 fn test_01() {
     let mut car = car::Car {
@@ -146,27 +142,3 @@ fn test_01() {
 
 //     assert_eq!(car.engines, vec![4.2, 1.5]);
 // }
-
-// #[test]
-// fn test_03() {
-//     type Dep<'o> = &'o mut i64;
-
-//     pac_cell::pac_cell!(
-//         struct Hello {
-//             owner: i64,
-//             dependent: Dep,
-//         }
-//     );
-
-//     let mut pac = Hello::new(10, |h| h);
-
-//     let initial = pac.with_mut(|dep| {
-//         let i = **dep;
-//         **dep = 12;
-//         i
-//     });
-//     assert_eq!(initial, 10);
-
-//     let hello_again = pac.into_owned();
-//     assert_eq!(hello_again, 12);
-// }
@@ -0,0 +1,35 @@
+//! Tests for the `pac_cell!` macro and its compile-fail guarantees.
+//!
+//! These live in their own target (rather than alongside the hand-written
+//! `EngineAndFuel` example in `tests/it`) so they build against nothing but the
+//! crate's public API.
+
+#[test]
+fn macro_roundtrip() {
+    type Dep<'o> = &'o mut i64;
+
+    pac_cell::pac_cell!(
+        struct Hello {
+            owner: i64,
+            dependent: Dep,
+        }
+    );
+
+    let mut pac = Hello::new(10, |h| h);
+
+    let initial = pac.with_mut(|dep| {
+        let i = **dep;
+        **dep = 12;
+        i
+    });
+    assert_eq!(initial, 10);
+
+    let hello_again = pac.into_owned();
+    assert_eq!(hello_again, 12);
+}
+
+#[test]
+fn compile_fail() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/compile-fail/*.rs");
+}
@@ -0,0 +1,42 @@
+//! Tests that [pac_cell::PacRefCell]'s runtime borrow conflict detection,
+//! the whole point of the type, actually fires. The doc example only calls
+//! `with_ref`/`with_mut` back to back, where each guard is dropped before the
+//! next call, so it can never exercise a conflict.
+
+struct Hello {
+    world: i64,
+}
+
+#[test]
+#[should_panic(expected = "child is already mutably borrowed")]
+fn with_ref_panics_while_with_mut_is_held() {
+    let pac = pac_cell::PacRefCell::new(Hello { world: 10 }, |h| &mut h.world);
+    pac.with_mut(|_world| {
+        pac.with_ref(|_world| {});
+    });
+}
+
+#[test]
+#[should_panic(expected = "child is already borrowed")]
+fn with_mut_panics_while_with_ref_is_held() {
+    let pac = pac_cell::PacRefCell::new(Hello { world: 10 }, |h| &mut h.world);
+    pac.with_ref(|_world| {
+        pac.with_mut(|_world| {});
+    });
+}
+
+#[test]
+fn try_with_ref_returns_err_while_with_mut_is_held() {
+    let pac = pac_cell::PacRefCell::new(Hello { world: 10 }, |h| &mut h.world);
+    pac.with_mut(|_world| {
+        assert!(pac.try_with_ref(|_world| {}).is_err());
+    });
+}
+
+#[test]
+fn try_with_mut_returns_err_while_with_ref_is_held() {
+    let pac = pac_cell::PacRefCell::new(Hello { world: 10 }, |h| &mut h.world);
+    pac.with_ref(|_world| {
+        assert!(pac.try_with_mut(|_world| {}).is_err());
+    });
+}